@@ -2,6 +2,7 @@ use fsearch_core::{
     DataType, Element, ElementBuilder, PluginAction, PluginActionType, PluginResponse,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs::{File, ReadDir};
 use std::io::prelude::*;
 use std::path::PathBuf;
@@ -16,6 +17,7 @@ use xdgkit::user_dirs::UserDirs;
 const CACHE_PATH: &str = "/tmp/fsearch_desktop_cache.json";
 const DEFAULT_ICON_PATH: &str =
     "/usr/share/icons/Adwaita/scalable/mimetypes/application-x-executable.svg";
+const USAGE_FILE_NAME: &str = "fsearch_launcher_usage.json";
 
 /// Main entry point for the application, it search for the given app name in desktop files and print the result
 fn main() {
@@ -32,6 +34,13 @@ fn main() {
         return;
     }
 
+    if query == "--record-launch" {
+        if let Some(exec) = args.get(2) {
+            record_launch(exec);
+        }
+        return;
+    }
+
     let result = search(query);
     if result.is_none() {
         let response = PluginResponse {
@@ -53,7 +62,7 @@ fn main() {
     for (i, entry) in result {
         if i == 0 {
             icon = entry.icon.clone();
-            exec = entry.exec.clone();
+            exec = resolve_exec(&entry);
         }
         let element = entry_to_element(&entry);
         elements.push(element);
@@ -104,11 +113,42 @@ fn entry_to_element(entry: &DesktopEntryBase) -> Element {
         .text(&entry.name)
         .build();
 
+    let mut children = vec![icon, label];
+    children.extend(
+        entry
+            .actions
+            .iter()
+            .map(|action| action_to_element(action, entry)),
+    );
+
     ElementBuilder::new(DataType::EventBox)
         .id("LauncherBox")
+        .children(children)
+        .on_click(PluginAction {
+            action: PluginActionType::Launch(resolve_exec(entry)),
+            close_after_run: Some(true),
+        })
+        .build()
+}
+
+/// Build the child element for a `[Desktop Action]`, e.g. Firefox's
+/// "New Private Window", nested inside the main Firefox entry's box.
+fn action_to_element(action: &DesktopAction, parent: &DesktopEntryBase) -> Element {
+    let icon = ElementBuilder::new(DataType::Image)
+        .id("LauncherActionIcon")
+        .image_path(action.icon.as_deref().unwrap_or(DEFAULT_ICON_PATH))
+        .build();
+
+    let label = ElementBuilder::new(DataType::Label)
+        .id("LauncherActionLabel")
+        .text(&action.name)
+        .build();
+
+    ElementBuilder::new(DataType::EventBox)
+        .id("LauncherAction")
         .children(vec![icon, label])
         .on_click(PluginAction {
-            action: PluginActionType::Launch(String::from(&entry.exec)),
+            action: PluginActionType::Launch(resolve_action_exec(action, parent)),
             close_after_run: Some(true),
         })
         .build()
@@ -121,12 +161,552 @@ struct DesktopEntryBase {
     icon: Option<String>,
     comment: Option<String>,
     generic_name: Option<String>,
+    desktop_file_path: Option<String>,
+    #[serde(default)]
+    terminal: bool,
+    #[serde(default)]
+    actions: Vec<DesktopAction>,
+}
+
+/// A `[Desktop Action <id>]` sub-command declared alongside the main entry,
+/// e.g. Firefox's "New Private Window".
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct DesktopAction {
+    name: String,
+    exec: String,
+    icon: Option<String>,
+}
+
+/// Parse the `[Desktop Action <id>]` groups listed in `Actions=`, in the
+/// order they're declared. The crate's `.desktop` parser only understands
+/// the main `[Desktop Entry]` group, so these are parsed by hand here.
+fn parse_desktop_actions(contents: &str) -> Vec<DesktopAction> {
+    let action_ids: Vec<&str> = contents
+        .lines()
+        .find(|line| line.starts_with("Actions="))
+        .map(|line| {
+            line.trim_start_matches("Actions=")
+                .split(';')
+                .filter(|id| !id.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut actions = Vec::new();
+    for id in action_ids {
+        let header = format!("[Desktop Action {}]", id);
+        let Some(start) = contents.find(&header) else {
+            continue;
+        };
+        let section = &contents[start + header.len()..];
+        let section = section.split("\n[").next().unwrap_or(section);
+
+        let mut name = None;
+        let mut exec = None;
+        let mut icon = None;
+        for line in section.lines() {
+            let line = line.trim();
+            if let Some(value) = line.strip_prefix("Name=") {
+                name = Some(value.to_string());
+            } else if let Some(value) = line.strip_prefix("Exec=") {
+                exec = Some(value.to_string());
+            } else if let Some(value) = line.strip_prefix("Icon=") {
+                icon = Some(value.to_string());
+            }
+        }
+
+        if let (Some(name), Some(exec)) = (name, exec) {
+            actions.push(DesktopAction {
+                name,
+                exec,
+                icon: icon.and_then(get_icon_path),
+            });
+        }
+    }
+
+    actions
+}
+
+/// The `$XDG_CURRENT_DESKTOP` entries, used to evaluate `OnlyShowIn`/`NotShowIn`.
+fn current_desktops() -> Vec<String> {
+    std::env::var("XDG_CURRENT_DESKTOP")
+        .unwrap_or_default()
+        .split(':')
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .collect()
+}
+
+/// Whether a raw `.desktop` entry should be shown in the launcher at all,
+/// honoring `NoDisplay`, `Hidden`, `OnlyShowIn` and `NotShowIn`.
+fn is_displayable(entry: &DesktopEntry) -> bool {
+    if entry.no_display.unwrap_or(false) || entry.hidden.unwrap_or(false) {
+        return false;
+    }
+
+    let current = current_desktops();
+
+    if let Some(only_show_in) = &entry.only_show_in {
+        if !only_show_in.iter().any(|d| current.contains(d)) {
+            return false;
+        }
+    }
+
+    if let Some(not_show_in) = &entry.not_show_in {
+        if not_show_in.iter().any(|d| current.contains(d)) {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// The terminal emulator to wrap `Terminal=true` entries in, honoring
+/// `$TERMINAL` and falling back to `x-terminal-emulator`.
+fn terminal_emulator() -> String {
+    match std::env::var("TERMINAL") {
+        Ok(term) if !term.is_empty() => term,
+        _ => "x-terminal-emulator".to_string(),
+    }
+}
+
+/// Expand the field codes the `.desktop` spec allows in `Exec=` (`%f`, `%F`,
+/// `%u`, `%U`, `%i`, `%c`, `%k`, `%%`). Since we're launching without a
+/// file/URI argument, `%f`/`%F`/`%u`/`%U` are simply dropped.
+fn expand_field_codes(
+    exec: &str,
+    name: &str,
+    icon: Option<&String>,
+    desktop_file_path: Option<&String>,
+) -> String {
+    let mut resolved = String::with_capacity(exec.len());
+    let mut chars = exec.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            resolved.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('%') => resolved.push('%'),
+            Some('f') | Some('F') | Some('u') | Some('U') => {}
+            Some('i') => {
+                if let Some(icon) = icon {
+                    resolved.push_str("--icon ");
+                    resolved.push_str(icon);
+                }
+            }
+            Some('c') => resolved.push_str(name),
+            Some('k') => {
+                if let Some(path) = desktop_file_path {
+                    resolved.push_str(path);
+                }
+            }
+            Some(_) | None => {}
+        }
+    }
+
+    resolved.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Resolve the launchable command for an entry's main `Exec=`, wrapping it
+/// in the user's terminal emulator when the entry has `Terminal=true`,
+/// normalizing the sandbox environment,
+/// and chaining in a launch-recording call (see `wrap_record_launch`).
+fn resolve_exec(entry: &DesktopEntryBase) -> String {
+    let resolved = expand_field_codes(
+        &entry.exec,
+        &entry.name,
+        entry.icon.as_ref(),
+        entry.desktop_file_path.as_ref(),
+    );
+
+    let resolved = if entry.terminal {
+        format!("{} -e {}", terminal_emulator(), resolved)
+    } else {
+        resolved
+    };
+
+    let resolved = apply_sandbox_env(resolved);
+    wrap_record_launch(resolved, usage_key(entry))
+}
+
+/// Resolve the launchable command for one of an entry's `[Desktop Action]`
+/// sub-commands, honoring the parent entry's `Terminal=true` like `resolve_exec`.
+fn resolve_action_exec(action: &DesktopAction, parent: &DesktopEntryBase) -> String {
+    let resolved = expand_field_codes(
+        &action.exec,
+        &action.name,
+        action.icon.as_ref(),
+        parent.desktop_file_path.as_ref(),
+    );
+
+    let resolved = if parent.terminal {
+        format!("{} -e {}", terminal_emulator(), resolved)
+    } else {
+        resolved
+    };
+
+    let resolved = apply_sandbox_env(resolved);
+    wrap_record_launch(resolved, &action_usage_key(action, parent))
+}
+
+const SANDBOX_MARKER_VARS: &[&str] = &["FLATPAK_ID", "APPIMAGE", "SNAP"];
+
+/// Env vars that leak host-polluting, sandbox-rewritten pathlists into every
+/// launched app (PATH, library search path, XDG data dirs, GTK/GStreamer
+/// plugin paths).
+const SANDBOX_PATHLIST_VARS: &[&str] = &[
+    "PATH",
+    "LD_LIBRARY_PATH",
+    "XDG_DATA_DIRS",
+    "GST_PLUGIN_SYSTEM_PATH",
+    "GST_PLUGIN_PATH",
+    "GI_TYPELIB_PATH",
+    "GTK_PATH",
+];
+
+/// Whether fsearch itself appears to be running inside a Flatpak, AppImage
+/// or Snap sandbox.
+fn is_sandboxed() -> bool {
+    SANDBOX_MARKER_VARS
+        .iter()
+        .any(|var| std::env::var(var).is_ok())
+}
+
+fn is_sandbox_path(path: &str) -> bool {
+    const SANDBOX_PREFIXES: &[&str] = &["/app/", "/run/host/", "/var/lib/flatpak/", "/snap/"];
+    if SANDBOX_PREFIXES
+        .iter()
+        .any(|prefix| path.starts_with(prefix))
+    {
+        return true;
+    }
+
+    match std::env::var("APPDIR") {
+        Ok(app_dir) if !app_dir.is_empty() => path.starts_with(&app_dir),
+        _ => false,
+    }
+}
+
+/// De-duplicate a `:`-separated pathlist, preferring system paths over the
+/// ones injected by the sandbox runtime.
+fn normalize_pathlist(value: &str) -> String {
+    let mut seen = std::collections::HashSet::new();
+    let mut system = Vec::new();
+    let mut sandboxed = Vec::new();
+
+    for entry in value.split(':').filter(|e| !e.is_empty()) {
+        if !seen.insert(entry) {
+            continue;
+        }
+
+        if is_sandbox_path(entry) {
+            sandboxed.push(entry);
+        } else {
+            system.push(entry);
+        }
+    }
+
+    system
+        .into_iter()
+        .chain(sandboxed)
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// When fsearch is itself sandboxed, prefix the launch command with an
+/// `env` call that rewrites the pathlist variables so the launched app
+/// sees a clean, host-like environment instead of our sandboxed one.
+fn apply_sandbox_env(exec: String) -> String {
+    if !is_sandboxed() {
+        return exec;
+    }
+
+    let overrides: String = SANDBOX_PATHLIST_VARS
+        .iter()
+        .filter_map(|var| std::env::var(var).ok().map(|value| (var, value)))
+        .map(|(var, value)| format!("{}={} ", var, shell_quote(&normalize_pathlist(&value))))
+        .collect();
+
+    if overrides.is_empty() {
+        return exec;
+    }
+
+    format!("env {}{}", overrides, exec)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct CachedEntries {
     entries: Vec<DesktopEntryBase>,
     last_update: u64,
+    #[serde(default)]
+    scanned_dirs: Vec<String>,
+    #[serde(default)]
+    max_dir_mtime: u64,
+}
+
+/// The application directories we scan for `.desktop` files, in priority
+/// order (falls back to the usual FHS/XDG locations when `applications()`
+/// can't be determined).
+fn application_dirs() -> Vec<String> {
+    let user_dirs = UserDirs::new();
+    let homdir = std::env::var("HOME").unwrap_or_default();
+    let desktop_path = user_dirs.desktop.replace("$HOME", &homdir);
+
+    if let Ok(apps) = applications() {
+        apps.split(':').map(|s| s.to_string()).collect()
+    } else {
+        vec![
+            "/usr/share/applications".to_string(),
+            "/usr/local/share/applications".to_string(),
+            format!("{}/.local/share/applications", homdir),
+            desktop_path,
+            "/var/lib/flatpak/exports/share/applications".to_string(),
+        ]
+    }
+}
+
+/// The most recent mtime across every scanned app directory, used to detect
+/// that something was installed/uninstalled since the cache was built.
+fn max_dirs_mtime(dirs: &[String]) -> u64 {
+    dirs.iter()
+        .filter_map(|dir| std::fs::metadata(dir).ok())
+        .filter_map(|meta| meta.modified().ok())
+        .filter_map(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+        .max()
+        .unwrap_or(0)
+}
+
+/// Whether the scanned directories changed, or any of them were touched,
+/// since the cache was last built.
+fn is_cache_stale(cache_entries: &CachedEntries) -> bool {
+    let current_dirs = application_dirs();
+    if current_dirs != cache_entries.scanned_dirs {
+        return true;
+    }
+
+    max_dirs_mtime(&current_dirs) > cache_entries.max_dir_mtime
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+struct UsageRecord {
+    count: u64,
+    last_launched: u64,
+}
+
+/// Path to the persisted `exec -> UsageRecord` map, honoring `$XDG_CACHE_HOME`
+/// like the rest of the desktop ecosystem and falling back to `~/.cache`.
+fn usage_store_path() -> PathBuf {
+    if let Ok(cache_home) = std::env::var("XDG_CACHE_HOME") {
+        if !cache_home.is_empty() {
+            return PathBuf::from(cache_home).join(USAGE_FILE_NAME);
+        }
+    }
+
+    let home = std::env::var("HOME").unwrap_or_default();
+    PathBuf::from(home).join(".cache").join(USAGE_FILE_NAME)
+}
+
+fn load_usage() -> HashMap<String, UsageRecord> {
+    let path = usage_store_path();
+    let Ok(mut file) = File::open(path) else {
+        return HashMap::new();
+    };
+
+    let mut contents = String::new();
+    if file.read_to_string(&mut contents).is_err() {
+        return HashMap::new();
+    }
+
+    serde_json::from_str(&contents).unwrap_or_default()
+}
+
+fn save_usage(usage: &HashMap<String, UsageRecord>) {
+    let path = usage_store_path();
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+
+    if let Ok(serialized) = serde_json::to_string(usage) {
+        if let Ok(mut file) = File::create(path) {
+            let _ = file.write_all(serialized.as_bytes());
+        }
+    }
+}
+
+/// A usage-history key for an entry: the `.desktop` file path, or the raw
+/// `Exec=` as a fallback. Stays stable across env changes, unlike the
+/// fully resolved launch command.
+fn usage_key(entry: &DesktopEntryBase) -> &str {
+    entry
+        .desktop_file_path
+        .as_deref()
+        .unwrap_or(entry.exec.as_str())
+}
+
+/// `usage_key`, scoped to one of an entry's `[Desktop Action]` sub-commands.
+fn action_usage_key(action: &DesktopAction, parent: &DesktopEntryBase) -> String {
+    match parent.desktop_file_path.as_deref() {
+        Some(path) => format!("{}#{}", path, action.name),
+        None => action.exec.clone(),
+    }
+}
+
+/// Chain a `--record-launch <key>` call for this binary in front of `exec`,
+/// so that the host actually firing the launch also records it.
+fn wrap_record_launch(exec: String, key: &str) -> String {
+    let Ok(self_path) = std::env::current_exe() else {
+        return exec;
+    };
+    let Some(self_path) = self_path.to_str() else {
+        return exec;
+    };
+
+    format!(
+        "sh -c {}",
+        shell_quote(&format!(
+            "{} --record-launch {} >/dev/null 2>&1; exec {}",
+            shell_quote(self_path),
+            shell_quote(key),
+            exec
+        ))
+    )
+}
+
+/// Bump the launch count and last-launched timestamp for a usage key (see
+/// `usage_key`/`action_usage_key`).
+fn record_launch(exec: &str) {
+    let mut usage = load_usage();
+    let record = usage.entry(exec.to_string()).or_default();
+    record.count += 1;
+    record.last_launched = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    save_usage(&usage);
+}
+
+/// Bucket the age of a last launch into a recency weight, most recent first.
+fn recency_weight(last_launched: u64, now: u64) -> u64 {
+    let age = now.saturating_sub(last_launched);
+    const HOUR: u64 = 60 * 60;
+    if age <= 4 * HOUR {
+        100
+    } else if age <= 24 * HOUR {
+        70
+    } else if age <= 7 * 24 * HOUR {
+        50
+    } else if age <= 30 * 24 * HOUR {
+        30
+    } else {
+        10
+    }
+}
+
+/// `count * recency_weight`, 0 for entries that have never been launched.
+fn frecency_score(exec: &str, usage: &HashMap<String, UsageRecord>, now: u64) -> u64 {
+    match usage.get(exec) {
+        Some(record) => record.count * recency_weight(record.last_launched, now),
+        None => 0,
+    }
+}
+
+/// Sort `matches` by fuzzy match quality against `query`, falling back to
+/// frecency and then name, and truncate to `limit`.
+fn rank_matches(
+    mut matches: Vec<DesktopEntryBase>,
+    query: &str,
+    limit: usize,
+) -> Vec<DesktopEntryBase> {
+    let usage = load_usage();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    matches.sort_by(|a, b| {
+        let score_a = entry_fuzzy_score(
+            query,
+            Some(&a.name),
+            a.generic_name.as_deref(),
+            a.comment.as_deref(),
+        )
+        .unwrap_or(i32::MIN);
+        let score_b = entry_fuzzy_score(
+            query,
+            Some(&b.name),
+            b.generic_name.as_deref(),
+            b.comment.as_deref(),
+        )
+        .unwrap_or(i32::MIN);
+
+        score_b.cmp(&score_a).then_with(|| {
+            let frecency_a = frecency_score(usage_key(a), &usage, now);
+            let frecency_b = frecency_score(usage_key(b), &usage, now);
+            frecency_b
+                .cmp(&frecency_a)
+                .then_with(|| a.name.cmp(&b.name))
+        })
+    });
+
+    matches.truncate(limit);
+    matches
+}
+
+/// Score `candidate` against `query` as an ordered fuzzy subsequence match,
+/// rewarding word-boundary and consecutive-char hits; `None` if `query`
+/// isn't a subsequence of `candidate` at all.
+fn fuzzy_match_score(query: &str, candidate: &str) -> Option<i32> {
+    if query.is_empty() {
+        return Some(0);
+    }
+
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    let mut cand_idx = 0;
+    let mut last_match: Option<usize> = None;
+    let mut score = 0i32;
+
+    for q in query.to_lowercase().chars() {
+        let matched = (cand_idx..candidate.len()).find(|&i| candidate[i] == q)?;
+
+        score += 10;
+
+        let at_boundary = matched == 0 || matches!(candidate[matched - 1], ' ' | '-' | '_');
+        if at_boundary {
+            score += 15;
+        }
+
+        match last_match {
+            Some(prev) if matched == prev + 1 => score += 5,
+            Some(prev) => score -= (matched - prev - 1) as i32,
+            None => {}
+        }
+
+        last_match = Some(matched);
+        cand_idx = matched + 1;
+    }
+
+    Some(score)
+}
+
+/// Score an entry against `query` by trying its name, then generic name,
+/// then comment, in that order, returning the first field that matches.
+fn entry_fuzzy_score(
+    query: &str,
+    name: Option<&str>,
+    generic_name: Option<&str>,
+    comment: Option<&str>,
+) -> Option<i32> {
+    name.and_then(|name| fuzzy_match_score(query, name))
+        .or_else(|| generic_name.and_then(|generic_name| fuzzy_match_score(query, generic_name)))
+        .or_else(|| comment.and_then(|comment| fuzzy_match_score(query, comment)))
 }
 
 fn get_icon_path(icon_name: String) -> Option<String> {
@@ -141,14 +721,10 @@ fn get_icon_path(icon_name: String) -> Option<String> {
     None
 }
 
-fn get_desktop_entry(query: String, dir: ReadDir, max: usize) -> Vec<DesktopEntryBase> {
-    let mut matches = Vec::<DesktopEntryBase>::new();
+fn get_desktop_entry(query: String, dir: ReadDir) -> Vec<DesktopEntryBase> {
+    let mut scored = Vec::<(DesktopEntryBase, i32)>::new();
 
     for file in dir {
-        if matches.len() >= max {
-            break;
-        }
-
         if file.is_err() {
             continue;
         }
@@ -157,39 +733,49 @@ fn get_desktop_entry(query: String, dir: ReadDir, max: usize) -> Vec<DesktopEntr
         let path = file.path();
         let file_name = path.file_name().unwrap().to_str().unwrap();
         if file_name.ends_with(".desktop") {
+            let desktop_file_path = path.to_str().map(|s| s.to_string());
             let mut file = File::open(path).unwrap();
             let mut contents = String::new();
             file.read_to_string(&mut contents).unwrap();
+            let actions = parse_desktop_actions(&contents);
             let entry = DesktopEntry::read(contents);
-            if entry.name.is_some() {
-                let name = entry.name.clone().unwrap();
-                if name.to_lowercase().contains(query.to_lowercase().as_str()) {
-                    let base = DesktopEntryBase {
-                        name: entry.name.unwrap(),
-                        exec: entry.exec.unwrap_or("".to_string()),
-                        generic_name: entry.generic_name,
-                        icon: get_icon_path(entry.icon.unwrap_or(DEFAULT_ICON_PATH.to_string())),
-                        comment: entry.comment,
-                    };
-                    matches.push(base);
-                }
-            } else if entry.generic_name.is_some() {
-                let name = entry.generic_name.clone().unwrap();
-                if name.to_lowercase().contains(query.to_lowercase().as_str()) {
-                    let base = DesktopEntryBase {
-                        name: entry.name.unwrap(),
-                        exec: entry.exec.unwrap_or("".to_string()),
-                        generic_name: entry.generic_name,
-                        icon: get_icon_path(entry.icon.unwrap_or(DEFAULT_ICON_PATH.to_string())),
-                        comment: entry.comment,
-                    };
-                    matches.push(base);
-                }
+
+            if !is_displayable(&entry) {
+                continue;
             }
+
+            let Some(name) = entry.name.clone().or_else(|| entry.generic_name.clone()) else {
+                continue;
+            };
+
+            let Some(score) = entry_fuzzy_score(
+                &query,
+                entry.name.as_deref(),
+                entry.generic_name.as_deref(),
+                entry.comment.as_deref(),
+            ) else {
+                continue;
+            };
+
+            let terminal = entry.terminal.unwrap_or(false);
+            let base = DesktopEntryBase {
+                name,
+                exec: entry.exec.unwrap_or("".to_string()),
+                generic_name: entry.generic_name,
+                icon: get_icon_path(entry.icon.unwrap_or(DEFAULT_ICON_PATH.to_string())),
+                comment: entry.comment,
+                desktop_file_path,
+                terminal,
+                actions,
+            };
+            scored.push((base, score));
         }
     }
 
-    matches
+    scored.sort_by(|(a, score_a), (b, score_b)| {
+        score_b.cmp(score_a).then_with(|| a.name.cmp(&b.name))
+    });
+    scored.into_iter().map(|(entry, _)| entry).collect()
 }
 
 fn get_cache() -> Option<String> {
@@ -217,6 +803,7 @@ fn has_cache() -> bool {
 
 /// Create a cache of all desktop files in the system to /tmp/fsearch_desktop_cache
 fn update_desktop_cache() {
+    let scanned_dirs = application_dirs();
     let matches = get_matches("", 1000, false);
     let cached_entries = CachedEntries {
         entries: matches,
@@ -224,6 +811,8 @@ fn update_desktop_cache() {
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_secs(),
+        max_dir_mtime: max_dirs_mtime(&scanned_dirs),
+        scanned_dirs,
     };
 
     let cache = serde_json::to_string(&cached_entries).unwrap();
@@ -236,90 +825,57 @@ fn get_matches(query: &str, limit: usize, use_cache: bool) -> Vec<DesktopEntryBa
     let query = query.to_lowercase();
 
     if use_cache && has_cache() {
-        let mut matches = Vec::<DesktopEntryBase>::new();
-        if let Some(cache_entries) = get_cache_entries() {
-            let mut count = 0;
-            for entry in cache_entries.entries {
-                if count >= limit {
-                    break;
-                }
+        let cache_entries = match get_cache_entries() {
+            Some(cache_entries) if !is_cache_stale(&cache_entries) => Some(cache_entries),
+            _ => {
+                update_desktop_cache();
+                get_cache_entries()
+            }
+        };
 
-                if entry.name.to_lowercase().contains(&query) {
-                    matches.push(entry);
-                    count += 1;
-                } else if let Some(generic_name) = &entry.generic_name {
-                    if generic_name.to_lowercase().contains(&query) {
-                        matches.push(entry);
-                        count += 1;
-                    }
-                } else if let Some(comment) = &entry.comment {
-                    if comment.to_lowercase().contains(&query) {
-                        matches.push(entry);
-                        count += 1;
-                    }
+        let mut candidates = Vec::<DesktopEntryBase>::new();
+        if let Some(cache_entries) = cache_entries {
+            for entry in cache_entries.entries {
+                let matched = entry_fuzzy_score(
+                    &query,
+                    Some(&entry.name),
+                    entry.generic_name.as_deref(),
+                    entry.comment.as_deref(),
+                )
+                .is_some();
+                if matched {
+                    candidates.push(entry);
                 }
             }
         }
 
-        return matches;
+        return rank_matches(candidates, &query, limit);
     }
 
     let matches = Arc::new(Mutex::new(Vec::<DesktopEntryBase>::new()));
-    let user_dirs = Arc::new(UserDirs::new());
-    let homdir = std::env::var("HOME").unwrap_or("".to_string());
-    let desktop_path = user_dirs.desktop.replace("$HOME", homdir.as_str());
 
-    let mut threads: Vec<_> = vec![];
-
-    if let Ok(apps) = applications() {
-        let apps: Vec<_> = apps.split(':').collect();
-        for app_folder in apps {
-            threads.push(spawn_thread(app_folder.to_string(), limit, matches.clone()))
-        }
-    } else {
-        threads = vec![
-            spawn_thread(
-                "/usr/share/applications".to_string(),
-                limit,
-                matches.clone(),
-            ),
-            spawn_thread(
-                "/usr/local/share/applications".to_string(),
-                limit,
-                matches.clone(),
-            ),
-            spawn_thread(
-                format!("{}/.local/share/applications", homdir),
-                limit,
-                matches.clone(),
-            ),
-            spawn_thread(desktop_path, limit, matches.clone()),
-            spawn_thread(
-                "/var/lib/flatpak/exports/share/applications".to_string(),
-                limit,
-                matches.clone(),
-            ),
-        ];
-    }
+    let threads: Vec<_> = application_dirs()
+        .into_iter()
+        .map(|app_folder| spawn_thread(app_folder, query.clone(), matches.clone()))
+        .collect();
 
     for handle in threads {
         handle.join().unwrap();
     }
 
-    let mut locked_matches = matches.lock().unwrap();
-    locked_matches.sort_by(|a, b| a.name.cmp(&b.name));
-    Vec::from(locked_matches.as_slice())
+    let locked_matches = matches.lock().unwrap();
+    rank_matches(locked_matches.clone(), &query, limit)
 }
 
 fn spawn_thread(
     dir: String,
-    limit: usize,
+    query: String,
     matches: Arc<Mutex<Vec<DesktopEntryBase>>>,
 ) -> thread::JoinHandle<()> {
     let matches_clone = Arc::clone(&matches);
     thread::spawn(move || {
         if let Ok(files) = std::fs::read_dir(dir) {
-            let user_matches = get_desktop_entry("".to_string(), files, limit);
+            let user_matches = get_desktop_entry(query, files);
             let mut locked_matches = matches_clone.lock().unwrap();
             locked_matches.extend(user_matches);
         }
@@ -335,3 +891,85 @@ fn search(query: &str) -> Option<Vec<DesktopEntryBase>> {
     let matches = find_desktop_file(query);
     Some(matches)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recency_weight_buckets() {
+        const HOUR: u64 = 60 * 60;
+        let now = 10_000_000;
+
+        assert_eq!(recency_weight(now, now), 100);
+        assert_eq!(recency_weight(now - 4 * HOUR, now), 100);
+        assert_eq!(recency_weight(now - 4 * HOUR - 1, now), 70);
+        assert_eq!(recency_weight(now - 24 * HOUR, now), 70);
+        assert_eq!(recency_weight(now - 24 * HOUR - 1, now), 50);
+        assert_eq!(recency_weight(now - 7 * 24 * HOUR, now), 50);
+        assert_eq!(recency_weight(now - 7 * 24 * HOUR - 1, now), 30);
+        assert_eq!(recency_weight(now - 30 * 24 * HOUR, now), 30);
+        assert_eq!(recency_weight(now - 30 * 24 * HOUR - 1, now), 10);
+    }
+
+    #[test]
+    fn frecency_score_unlaunched_entry_is_zero() {
+        let usage = HashMap::new();
+        assert_eq!(
+            frecency_score("/usr/share/applications/foo.desktop", &usage, 1_000_000),
+            0
+        );
+    }
+
+    #[test]
+    fn frecency_score_multiplies_count_by_recency_weight() {
+        let mut usage = HashMap::new();
+        usage.insert(
+            "/usr/share/applications/foo.desktop".to_string(),
+            UsageRecord {
+                count: 5,
+                last_launched: 1_000_000,
+            },
+        );
+        assert_eq!(
+            frecency_score("/usr/share/applications/foo.desktop", &usage, 1_000_000),
+            500
+        );
+    }
+
+    #[test]
+    fn fuzzy_match_score_requires_an_ordered_subsequence() {
+        assert!(fuzzy_match_score("ff", "Firefox").is_some());
+        assert!(fuzzy_match_score("xf", "Firefox").is_none());
+    }
+
+    #[test]
+    fn fuzzy_match_score_empty_query_matches_anything() {
+        assert_eq!(fuzzy_match_score("", "Firefox"), Some(0));
+    }
+
+    #[test]
+    fn fuzzy_match_score_prefers_word_boundary_and_consecutive_matches() {
+        let boundary = fuzzy_match_score("fox", "Firefox").unwrap();
+        let mid_word = fuzzy_match_score("fox", "Reflowbox").unwrap();
+        assert!(boundary > mid_word);
+
+        let consecutive = fuzzy_match_score("fi", "Firefox").unwrap();
+        let scattered = fuzzy_match_score("fi", "Fast Viewer").unwrap();
+        assert!(consecutive > scattered);
+    }
+
+    #[test]
+    fn entry_fuzzy_score_falls_back_through_name_generic_name_comment() {
+        assert!(entry_fuzzy_score("fire", Some("Firefox"), None, None).is_some());
+        assert!(entry_fuzzy_score("brow", Some("Firefox"), Some("Web Browser"), None).is_some());
+        assert!(entry_fuzzy_score(
+            "internet",
+            Some("Firefox"),
+            Some("Web Browser"),
+            Some("Browse the internet")
+        )
+        .is_some());
+        assert!(entry_fuzzy_score("nomatch", Some("Firefox"), Some("Web Browser"), None).is_none());
+    }
+}